@@ -1,41 +1,256 @@
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
 
 use lazy_static::lazy_static;
 use lsp_types::ImplementationProviderCapability;
 use lsp_types::OneOf;
 use lsp_types::TypeDefinitionProviderCapability;
 use lsp_types::{
-    CompletionOptions, CompletionParams, CompletionResponse, GotoDefinitionParams,
-    GotoDefinitionResponse, InitializeParams, InitializeResult, InitializedParams, MessageType,
-    ServerCapabilities, ServerInfo,
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidChangeWatchedFilesParams,
+    DidChangeWatchedFilesRegistrationOptions, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DidSaveTextDocumentParams, ExecuteCommandOptions,
+    ExecuteCommandParams, FileSystemWatcher, GlobPattern, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability,
+    InitializeParams, InitializeResult, InitializedParams, MarkupContent, MarkupKind,
+    MessageType, Position, PositionEncodingKind, Range, ReferenceParams, Registration,
+    ServerCapabilities, ServerInfo, TextDocumentSyncCapability, TextDocumentSyncKind,
+    TextDocumentSyncOptions, TextDocumentSyncSaveOptions, Url,
 };
 use regex::Regex;
 use serde_yaml::Value;
-use tokio::fs;
-use tokio::io::AsyncBufReadExt;
-use tokio::io::BufReader;
 use tokio::sync::RwLock;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::{LanguageServer, LspService, Server};
 
 lazy_static! {
-    static ref HELPERS_RE: Regex = Regex::new(r"\{\{-?\s*define\s+([^\}]+)\s*-?\}\}").unwrap();
+    static ref HELPERS_RE: Regex = Regex::new(r#"define\s+"([^"]+)""#).unwrap();
+    /// Matches an `include "name"` or `template "name"` call, capturing the
+    /// quoted helper name.
+    static ref HELPER_CALL_RE: Regex = Regex::new(r#"(?:include|template)\s+"([^"]+)""#).unwrap();
     static ref STATEMENT_RE: Regex = Regex::new(r"\{\{-?\s*([^\}]+)\s*-?\}\}").unwrap();
     static ref RANGE_RE: Regex = Regex::new(r"range\s+(\$[\w]+),\s*(\$[\w]+)\s*:=").unwrap();
+    /// Matches the `Error: ... in template ... at <file>:<line>:<col>` (or
+    /// `Error: ... template: <file>:<line>:<col>`) lines `helm lint`/`helm
+    /// template` print to stderr on failure.
+    static ref HELM_ERROR_RE: Regex =
+        Regex::new(r"Error:.*?(?:in template[^:]*?\sat|template:)\s*(?P<file>\S+?):(?P<line>\d+):(?P<col>\d+)")
+            .unwrap();
+    /// Matches a trailing `.Values`, `.Chart`, or `.Release` reference (with
+    /// an optional dotted path after it) at the end of the text left of the
+    /// cursor, e.g. `.Values.image.` or `.Chart.Na`.
+    static ref COMPLETION_PATH_RE: Regex =
+        Regex::new(r"\.(Values|Chart|Release)((?:\.[A-Za-z0-9_]*)*)$").unwrap();
+    /// Matches a `.Values`, `.Chart`, or `.Release` reference (with an
+    /// optional dotted path after it) at the start of the whitespace-
+    /// delimited word under the cursor, used to resolve hover content.
+    static ref REFERENCE_RE: Regex =
+        Regex::new(r"^\.(Values|Chart|Release)((?:\.[A-Za-z0-9_]+)*)").unwrap();
+}
+
+/// How long to wait after the last edit before running `helm lint`/`helm
+/// template`, so a burst of keystrokes only spawns one `helm` process.
+const DIAGNOSTICS_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Maps each `.Chart.*` field name Helm templates use (the Go struct field
+/// name, PascalCase) to the corresponding key in the `Chart.yaml` that
+/// `helm show chart` returns (lowercase/camelCase).
+const CHART_FIELDS: &[(&str, &str)] = &[
+    ("APIVersion", "apiVersion"),
+    ("Name", "name"),
+    ("Version", "version"),
+    ("KubeVersion", "kubeVersion"),
+    ("Description", "description"),
+    ("Type", "type"),
+    ("Keywords", "keywords"),
+    ("Home", "home"),
+    ("Sources", "sources"),
+    ("Dependencies", "dependencies"),
+    ("Maintainers", "maintainers"),
+    ("Icon", "icon"),
+    ("AppVersion", "appVersion"),
+    ("Deprecated", "deprecated"),
+    ("Annotations", "annotations"),
+];
+
+/// Fixed set of `.Release.*` fields Helm exposes to templates.
+const RELEASE_FIELDS: &[(&str, &str)] = &[
+    ("Name", "The name of the release (not the chart)"),
+    ("Namespace", "The namespace the release is deployed into"),
+    ("IsUpgrade", "true if the current operation is an upgrade or rollback"),
+    ("IsInstall", "true if the current operation is an install"),
+    ("Revision", "The revision number for this release"),
+    ("Service", "The name of the releasing service, always `Helm`"),
+];
+
+/// The unit `Position.character` is counted in, negotiated with the client
+/// during `initialize` via `general.position_encodings`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OffsetEncoding {
+    Utf8,
+    Utf16,
+}
+
+impl Default for OffsetEncoding {
+    /// UTF-16 is the default and must always be supported by LSP servers.
+    fn default() -> Self {
+        Self::Utf16
+    }
+}
+
+impl From<OffsetEncoding> for PositionEncodingKind {
+    fn from(encoding: OffsetEncoding) -> Self {
+        match encoding {
+            OffsetEncoding::Utf8 => PositionEncodingKind::UTF8,
+            OffsetEncoding::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+}
+
+/// Converts a `Position.character` column (counted in `encoding`'s units)
+/// into a byte offset into `line`.
+fn line_byte_offset(line: &str, character: u32, encoding: OffsetEncoding) -> usize {
+    let mut remaining = character;
+    let mut offset = 0usize;
+    for c in line.chars() {
+        if remaining == 0 {
+            break;
+        }
+        let units = match encoding {
+            OffsetEncoding::Utf8 => c.len_utf8() as u32,
+            OffsetEncoding::Utf16 => c.len_utf16() as u32,
+        };
+        if units > remaining {
+            break;
+        }
+        remaining -= units;
+        offset += c.len_utf8();
+    }
+    offset
+}
+
+/// An open text document tracked entirely in memory, kept in sync with the
+/// client via `textDocument/didOpen`/`didChange`/`didClose` instead of being
+/// re-read from disk on every request.
+#[derive(Debug, Clone)]
+struct Document {
+    text: String,
+    /// Byte offset of the start of each line, rebuilt whenever `text` changes.
+    line_index: Vec<u32>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let line_index = Self::build_line_index(&text);
+        Self { text, line_index }
+    }
+
+    fn build_line_index(text: &str) -> Vec<u32> {
+        let mut index = vec![0u32];
+        index.extend(
+            text.bytes()
+                .enumerate()
+                .filter(|(_, b)| *b == b'\n')
+                .map(|(i, _)| (i + 1) as u32),
+        );
+        index
+    }
+
+    fn line(&self, line: u32) -> Option<&str> {
+        let start = *self.line_index.get(line as usize)? as usize;
+        let end = self
+            .line_index
+            .get(line as usize + 1)
+            .map(|&end| end as usize)
+            .unwrap_or(self.text.len());
+        Some(self.text[start..end].trim_end_matches(['\n', '\r']))
+    }
+
+    fn lines(&self) -> impl Iterator<Item = &str> {
+        (0..self.line_index.len()).map_while(|i| self.line(i as u32))
+    }
+
+    /// Converts a `Position` into a byte offset into `text`, interpreting
+    /// `character` per the negotiated `encoding`. Per the LSP spec, clients
+    /// may send a `line`/`character` past the end of the document, so both
+    /// are clamped rather than indexed directly.
+    fn position_to_offset(&self, position: &lsp_types::Position, encoding: OffsetEncoding) -> usize {
+        let max_line = self.line_index.len().saturating_sub(1) as u32;
+        let line_no = position.line.min(max_line);
+        let line_start = self.line_index[line_no as usize] as usize;
+        let line = self.line(line_no).unwrap_or("");
+        line_start + line_byte_offset(line, position.character, encoding)
+    }
+
+    fn apply_change(
+        &mut self,
+        change: lsp_types::TextDocumentContentChangeEvent,
+        encoding: OffsetEncoding,
+    ) {
+        match change.range {
+            Some(range) => {
+                let start = self.position_to_offset(&range.start, encoding);
+                let end = self.position_to_offset(&range.end, encoding);
+                self.text.replace_range(start..end, &change.text);
+            }
+            None => self.text = change.text,
+        }
+        self.line_index = Self::build_line_index(&self.text);
+    }
 }
 
 #[derive(Debug, Default)]
 struct Chart {
     values: RwLock<serde_yaml::Value>,
     metadata: RwLock<serde_yaml::Value>,
+    root: RwLock<Option<PathBuf>>,
+}
+
+/// State shared between `Backend` and the detached tasks it spawns (e.g. the
+/// debounced diagnostics run), so those tasks can hold an owned `Arc` clone
+/// instead of borrowing `&Backend` for their whole (potentially long) run.
+#[derive(Default)]
+struct Shared {
+    chart: Chart,
+    documents: RwLock<HashMap<Url, Document>>,
+    /// Diagnostics last published per document, so a re-run can tell which
+    /// files need to have their diagnostics cleared.
+    diagnostics: RwLock<HashMap<Url, Vec<Diagnostic>>>,
+    /// Bumped on every edit; a pending debounced diagnostics run bails out if
+    /// it no longer matches the latest value when its sleep elapses.
+    diagnostics_epoch: AtomicU64,
+    /// Bumped on every `spawn_reload_chart`; a reload bails out of writing its
+    /// result if a newer reload has since been spawned, so a burst of watched
+    /// file events can't let an older, slower reload clobber a newer one.
+    reload_epoch: AtomicU64,
+    /// Helper name -> its `define` site, indexed across every `*.tpl`/`*.yaml`
+    /// under `templates/`.
+    helpers: RwLock<HashMap<String, lsp_types::Location>>,
+    /// Helper name -> every `include`/`template` call-site referencing it.
+    helper_refs: RwLock<HashMap<String, Vec<lsp_types::Location>>>,
+    /// Position encoding negotiated with the client in `initialize`.
+    encoding: RwLock<OffsetEncoding>,
 }
 
 // #[derive(Debug)]
 struct Backend {
     client: tower_lsp::Client,
-    chart: Chart,
+    shared: Arc<Shared>,
+}
+
+impl std::ops::Deref for Backend {
+    type Target = Shared;
+
+    fn deref(&self) -> &Shared {
+        &self.shared
+    }
 }
 
 #[allow(dead_code)]
@@ -119,24 +334,454 @@ impl Context {
     }
 }
 
+impl Backend {
+    /// Spawns a detached task that debounces a diagnostics run: waits out
+    /// `DIAGNOSTICS_DEBOUNCE`, then runs `helm lint`/`helm template` unless a
+    /// newer edit has come in since this call started.
+    ///
+    /// This returns immediately rather than awaiting the debounce and the
+    /// `helm` runs inline, since tower-lsp dispatches every request and
+    /// notification through one shared, concurrency-limited pool; blocking a
+    /// notification handler on this for hundreds of milliseconds would stall
+    /// unrelated completion/hover/goto-definition requests.
+    fn schedule_diagnostics(&self) {
+        let shared = self.shared.clone();
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            let epoch = shared.diagnostics_epoch.fetch_add(1, Ordering::SeqCst) + 1;
+            tokio::time::sleep(DIAGNOSTICS_DEBOUNCE).await;
+            if shared.diagnostics_epoch.load(Ordering::SeqCst) != epoch {
+                return;
+            }
+            Self::run_diagnostics(&shared, &client).await;
+        });
+    }
+
+    async fn run_diagnostics(shared: &Shared, client: &tower_lsp::Client) {
+        let Some(root) = shared.chart.root.read().await.clone() else {
+            return;
+        };
+
+        let lint = tokio::process::Command::new("helm")
+            .arg("lint")
+            .arg(&root)
+            .output()
+            .await;
+        let template = tokio::process::Command::new("helm")
+            .arg("template")
+            .arg(&root)
+            .output()
+            .await;
+
+        let mut by_file: HashMap<Url, Vec<Diagnostic>> = HashMap::new();
+        for output in [lint, template].into_iter().flatten() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            for line in stderr.lines() {
+                let Some(cap) = HELM_ERROR_RE.captures(line) else {
+                    continue;
+                };
+                let Some(uri) = Self::resolve_template_uri(&root, &cap["file"]) else {
+                    continue;
+                };
+                let line_no: u32 = cap["line"].parse::<u32>().unwrap_or(1).saturating_sub(1);
+                let col_no: u32 = cap["col"].parse::<u32>().unwrap_or(1).saturating_sub(1);
+
+                by_file.entry(uri).or_default().push(Diagnostic {
+                    range: Range {
+                        start: Position {
+                            line: line_no,
+                            character: col_no,
+                        },
+                        end: Position {
+                            line: line_no,
+                            character: col_no + 1,
+                        },
+                    },
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    source: Some("helm".into()),
+                    message: line.trim().to_string(),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let mut diagnostics = shared.diagnostics.write().await;
+        for uri in diagnostics.keys() {
+            if !by_file.contains_key(uri) {
+                client.publish_diagnostics(uri.clone(), vec![], None).await;
+            }
+        }
+        for (uri, diags) in &by_file {
+            client
+                .publish_diagnostics(uri.clone(), diags.clone(), None)
+                .await;
+        }
+        *diagnostics = by_file;
+    }
+
+    /// `helm`'s error output references templates relative to the chart
+    /// directory's parent (e.g. `mychart/templates/x.yaml`), so resolve them
+    /// from there rather than from the chart root itself.
+    fn resolve_template_uri(root: &Path, file: &str) -> Option<Url> {
+        let base = root.parent().unwrap_or(root);
+        Url::from_file_path(base.join(file)).ok()
+    }
+
+    /// Completes a dotted `path` (as captured by `COMPLETION_PATH_RE`, e.g.
+    /// `""`, `".image"`, or `".image."`) against `value`, returning the
+    /// matching children of whichever mapping the path resolves to.
+    fn complete_yaml_path(value: &Value, path: &str) -> Vec<CompletionItem> {
+        let full = path.trim_start_matches('.');
+        let mut segments: Vec<&str> = full.split('.').filter(|s| !s.is_empty()).collect();
+        let partial = if path.ends_with('.') || full.is_empty() {
+            ""
+        } else {
+            segments.pop().unwrap_or("")
+        };
+
+        let mut current = value;
+        for segment in segments {
+            let Value::Mapping(map) = current else {
+                return Vec::new();
+            };
+            let Some(next) = map.get(Value::String(segment.to_string())) else {
+                return Vec::new();
+            };
+            current = next;
+        }
+
+        let Value::Mapping(map) = current else {
+            return Vec::new();
+        };
+        map.iter()
+            .filter_map(|(k, v)| k.as_str().map(|k| (k, v)))
+            .filter(|(key, _)| key.starts_with(partial))
+            .map(|(key, val)| CompletionItem {
+                label: key.to_string(),
+                kind: Some(match val {
+                    Value::Mapping(_) => CompletionItemKind::FIELD,
+                    _ => CompletionItemKind::VALUE,
+                }),
+                detail: Some(Self::yaml_type_name(val).to_string()),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn resolve_yaml_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+        let mut current = value;
+        for segment in path.trim_start_matches('.').split('.').filter(|s| !s.is_empty()) {
+            let Value::Mapping(map) = current else {
+                return None;
+            };
+            current = map.get(Value::String(segment.to_string()))?;
+        }
+        Some(current)
+    }
+
+    /// Resolves a dotted `.Chart.*` path, mapping its first segment from the
+    /// PascalCase field name templates use to the lowercase/camelCase key
+    /// `helm show chart` actually stores it under (see `CHART_FIELDS`).
+    fn resolve_chart_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+        let full = path.trim_start_matches('.');
+        let mut segments = full.split('.').filter(|s| !s.is_empty());
+        let first = segments.next()?;
+        let (_, key) = CHART_FIELDS.iter().find(|(name, _)| *name == first)?;
+        let mut current = Self::resolve_yaml_path(value, key)?;
+        for segment in segments {
+            let Value::Mapping(map) = current else {
+                return None;
+            };
+            current = map.get(Value::String(segment.to_string()))?;
+        }
+        Some(current)
+    }
+
+    fn complete_chart_path(value: &Value, path: &str) -> Vec<CompletionItem> {
+        let full = path.trim_start_matches('.');
+        let mut segments: Vec<&str> = full.split('.').filter(|s| !s.is_empty()).collect();
+        let partial = if path.ends_with('.') || full.is_empty() {
+            ""
+        } else {
+            segments.pop().unwrap_or("")
+        };
+
+        if segments.is_empty() {
+            return CHART_FIELDS
+                .iter()
+                .filter(|(name, _)| name.starts_with(partial))
+                .map(|(name, key)| CompletionItem {
+                    label: (*name).to_string(),
+                    kind: Some(CompletionItemKind::FIELD),
+                    detail: Self::resolve_yaml_path(value, key)
+                        .map(|v| Self::yaml_type_name(v).to_string()),
+                    ..Default::default()
+                })
+                .collect();
+        }
+
+        let rest = format!(".{}", segments.join("."));
+        let Some(current) = Self::resolve_chart_path(value, &rest) else {
+            return Vec::new();
+        };
+        let Value::Mapping(map) = current else {
+            return Vec::new();
+        };
+        map.iter()
+            .filter_map(|(k, v)| k.as_str().map(|k| (k, v)))
+            .filter(|(key, _)| key.starts_with(partial))
+            .map(|(key, val)| CompletionItem {
+                label: key.to_string(),
+                kind: Some(match val {
+                    Value::Mapping(_) => CompletionItemKind::FIELD,
+                    _ => CompletionItemKind::VALUE,
+                }),
+                detail: Some(Self::yaml_type_name(val).to_string()),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn complete_release(path: &str) -> Vec<CompletionItem> {
+        let partial = path.trim_start_matches('.');
+        RELEASE_FIELDS
+            .iter()
+            .filter(|(name, _)| name.starts_with(partial))
+            .map(|(name, desc)| CompletionItem {
+                label: (*name).to_string(),
+                kind: Some(CompletionItemKind::FIELD),
+                detail: Some((*desc).to_string()),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn render_yaml_hover(value: &Value) -> String {
+        let yaml = serde_yaml::to_string(value).unwrap_or_default();
+        format!("```yaml\n{}```", yaml)
+    }
+
+    fn yaml_type_name(value: &Value) -> &'static str {
+        match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Sequence(_) => "array",
+            Value::Mapping(_) => "object",
+            Value::Tagged(_) => "tagged",
+        }
+    }
+
+    /// Spawns a detached task that re-runs `helm show values`/`helm show
+    /// chart` and rebuilds the helper index against the stored chart root.
+    ///
+    /// This returns immediately rather than awaiting the `helm` runs and the
+    /// `templates/` walk inline, for the same reason `schedule_diagnostics`
+    /// does: blocking a notification/request handler on it would stall
+    /// unrelated completion/hover/goto-definition requests sharing the same
+    /// concurrency-limited dispatch pool.
+    fn spawn_reload_chart(&self) {
+        let shared = self.shared.clone();
+        let client = self.client.clone();
+        let epoch = shared.reload_epoch.fetch_add(1, Ordering::SeqCst) + 1;
+        tokio::spawn(async move {
+            Self::reload_chart(&shared, &client, epoch).await;
+        });
+    }
+
+    /// Re-runs `helm show values`/`helm show chart` and rebuilds the helper
+    /// index against the stored chart root. Used on startup and whenever
+    /// `values.yaml`, `Chart.yaml`, or a `.tpl` file under `templates/`
+    /// changes on disk.
+    ///
+    /// A failure to run `helm` or parse its output (missing binary, unmet
+    /// chart dependencies, a YAML file mid-edit, ...) is logged to the
+    /// client rather than panicking, leaving the previously loaded values in
+    /// place. `epoch` is the value `reload_epoch` held when this reload was
+    /// spawned; if a newer reload has been spawned by the time the `helm`
+    /// runs finish, this one discards its result instead of writing stale
+    /// data over a newer reload's.
+    async fn reload_chart(shared: &Shared, client: &tower_lsp::Client, epoch: u64) {
+        let Some(root) = shared.chart.root.read().await.clone() else {
+            return;
+        };
+
+        let values = Self::load_chart_yaml(&root, "values").await;
+        let metadata = Self::load_chart_yaml(&root, "chart").await;
+
+        if shared.reload_epoch.load(Ordering::SeqCst) != epoch {
+            return;
+        }
+
+        match values {
+            Ok(values) => *shared.chart.values.write().await = values,
+            Err(err) => client.log_message(MessageType::ERROR, err).await,
+        }
+
+        match metadata {
+            Ok(metadata) => *shared.chart.metadata.write().await = metadata,
+            Err(err) => client.log_message(MessageType::ERROR, err).await,
+        }
+
+        Self::scan_helpers(shared, &root).await;
+    }
+
+    /// Runs `helm show <subcommand> <root>` and parses its stdout as YAML,
+    /// returning a human-readable error instead of panicking if the process
+    /// fails to run, exits non-zero, or produces unparseable output.
+    async fn load_chart_yaml(root: &Path, subcommand: &str) -> std::result::Result<serde_yaml::Value, String> {
+        let output = tokio::process::Command::new("helm")
+            .arg("show")
+            .arg(subcommand)
+            .arg(root)
+            .output()
+            .await
+            .map_err(|err| format!("failed to run `helm show {subcommand}`: {err}"))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "`helm show {subcommand}` failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        serde_yaml::from_slice(&output.stdout)
+            .map_err(|err| format!("failed to parse `helm show {subcommand}` output: {err}"))
+    }
+
+    /// Scans every `*.tpl`/`*.yaml` file under `<root>/templates` and
+    /// (re)builds the helper name -> definition/call-site indices.
+    async fn scan_helpers(shared: &Shared, root: &Path) {
+        let mut helpers = HashMap::new();
+        let mut helper_refs: HashMap<String, Vec<lsp_types::Location>> = HashMap::new();
+
+        let mut dirs = vec![root.join("templates")];
+        while let Some(dir) = dirs.pop() {
+            let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+                continue;
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let is_template = matches!(
+                    path.extension().and_then(|ext| ext.to_str()),
+                    Some("tpl") | Some("yaml") | Some("yml")
+                );
+                if !is_template {
+                    continue;
+                }
+                let (Ok(content), Ok(uri)) = (
+                    tokio::fs::read_to_string(&path).await,
+                    Url::from_file_path(&path),
+                ) else {
+                    continue;
+                };
+
+                for (lineno, line) in content.lines().enumerate() {
+                    let lineno = lineno as u32;
+                    if let Some(mat) = HELPERS_RE.captures(line).and_then(|cap| cap.get(1)) {
+                        helpers.insert(mat.as_str().to_string(), Self::location_at(&uri, lineno, mat.start(), mat.end()));
+                    }
+                    for cap in HELPER_CALL_RE.captures_iter(line) {
+                        let Some(mat) = cap.get(1) else { continue };
+                        helper_refs
+                            .entry(mat.as_str().to_string())
+                            .or_default()
+                            .push(Self::location_at(&uri, lineno, mat.start(), mat.end()));
+                    }
+                }
+            }
+        }
+
+        *shared.helpers.write().await = helpers;
+        *shared.helper_refs.write().await = helper_refs;
+    }
+
+    fn location_at(uri: &Url, line: u32, start: usize, end: usize) -> lsp_types::Location {
+        lsp_types::Location {
+            uri: uri.clone(),
+            range: Range {
+                start: Position {
+                    line,
+                    character: start as u32,
+                },
+                end: Position {
+                    line,
+                    character: end as u32,
+                },
+            },
+        }
+    }
+
+    /// Returns the whitespace-delimited token `character` falls within on
+    /// `line`, found by scanning left and right from the cursor for the
+    /// nearest space on each side.
+    fn word_at(line: &str, character: u32, encoding: OffsetEncoding) -> Option<&str> {
+        let byte = line_byte_offset(line, character, encoding);
+        let start = line[..byte].rfind(' ')?;
+        let end = line[byte..].find(' ').map(|i| byte + i)?;
+        Some(&line[start + 1..end])
+    }
+
+    /// Returns the helper name if `character` falls within the quoted
+    /// argument of an `include`/`template` call, or within the quoted name
+    /// of a `define`, on `line`.
+    fn helper_name_at(line: &str, character: u32, encoding: OffsetEncoding) -> Option<String> {
+        let byte = line_byte_offset(line, character, encoding);
+        HELPER_CALL_RE
+            .captures_iter(line)
+            .chain(HELPERS_RE.captures_iter(line))
+            .find_map(|cap| {
+                let mat = cap.get(1)?;
+                (mat.start() <= byte && byte <= mat.end()).then(|| mat.as_str().to_string())
+            })
+    }
+}
+
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
         eprintln!("init params from client: {:?}", params.client_info);
+
+        let offered_encodings = params
+            .capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_ref());
+        let encoding = match offered_encodings {
+            Some(encodings) if encodings.contains(&PositionEncodingKind::UTF8) => {
+                OffsetEncoding::Utf8
+            }
+            _ => OffsetEncoding::Utf16,
+        };
+        *self.encoding.write().await = encoding;
+
         let result = InitializeResult {
             capabilities: ServerCapabilities {
+                position_encoding: Some(encoding.into()),
                 completion_provider: Some(CompletionOptions {
                     trigger_characters: Some(vec![".".into(), " ".into()]),
                     ..Default::default()
                 }),
-                text_document_sync: None,
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..Default::default()
+                    },
+                )),
                 selection_range_provider: None,
-                hover_provider: None,
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
                 signature_help_provider: None,
                 definition_provider: Some(OneOf::Left(true)),
                 type_definition_provider: Some(TypeDefinitionProviderCapability::Simple(true)),
                 implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
-                references_provider: None,
+                references_provider: Some(OneOf::Left(true)),
                 document_highlight_provider: None,
                 document_symbol_provider: None,
                 workspace_symbol_provider: None,
@@ -150,7 +795,10 @@ impl LanguageServer for Backend {
                 color_provider: None,
                 folding_range_provider: None,
                 declaration_provider: None,
-                execute_command_provider: None,
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec!["helmls/reload".into()],
+                    work_done_progress_options: Default::default(),
+                }),
                 workspace: None,
                 call_hierarchy_provider: None,
                 semantic_tokens_provider: None,
@@ -158,6 +806,8 @@ impl LanguageServer for Backend {
                 linked_editing_range_provider: None,
                 experimental: None,
                 inlay_hint_provider: None,
+                diagnostic_provider: None,
+                inline_value_provider: None,
             },
             server_info: Some(ServerInfo {
                 name: "helmls".into(),
@@ -167,42 +817,9 @@ impl LanguageServer for Backend {
         };
 
         if let Some(url) = params.root_uri {
-            let output = tokio::process::Command::new("helm")
-                .arg("show")
-                .arg("values")
-                .arg(url.path())
-                .output()
-                .await
-                .unwrap();
-
-            let values: serde_yaml::Value = serde_yaml::from_slice(&output.stdout).unwrap();
-            *self.chart.values.write().await = values;
-
-            let output = tokio::process::Command::new("helm")
-                .arg("show")
-                .arg("chart")
-                .arg(url.path())
-                .output()
-                .await
-                .unwrap();
-
-            let metadata: serde_yaml::Value = serde_yaml::from_slice(&output.stdout).unwrap();
-            *self.chart.metadata.write().await = metadata;
-
-            let output =
-                tokio::fs::read_to_string(Path::new(url.path()).join("templates/_helpers.tpl"))
-                    .await
-                    .expect("read _helpers.tpl");
-
-            let templates: Vec<&str> = HELPERS_RE
-                .captures_iter(output.as_str())
-                .filter_map(|cap| cap.get(1))
-                .map(|mat| mat.as_str())
-                .collect();
-
-            for tmp in templates {
-                eprintln!("cap: {}", tmp);
-            }
+            *self.chart.root.write().await = Some(PathBuf::from(url.path()));
+            let epoch = self.shared.reload_epoch.fetch_add(1, Ordering::SeqCst) + 1;
+            Self::reload_chart(&self.shared, &self.client, epoch).await;
         }
 
         Ok(result)
@@ -213,6 +830,25 @@ impl LanguageServer for Backend {
         self.client
             .log_message(MessageType::INFO, "server initialized!")
             .await;
+
+        let watchers = ["**/values.yaml", "**/Chart.yaml", "**/templates/*.tpl"]
+            .into_iter()
+            .map(|pattern| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(pattern.into()),
+                kind: None,
+            })
+            .collect();
+        let registration = Registration {
+            id: "helmls/watch-chart-files".into(),
+            method: "workspace/didChangeWatchedFiles".into(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                watchers,
+            })
+            .ok(),
+        };
+        if let Err(err) = self.client.register_capability(vec![registration]).await {
+            eprintln!("failed to register file watchers: {err}");
+        }
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -220,45 +856,163 @@ impl LanguageServer for Backend {
         Ok(())
     }
 
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let doc = Document::new(params.text_document.text);
+        self.documents
+            .write()
+            .await
+            .insert(params.text_document.uri, doc);
+        self.schedule_diagnostics();
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        {
+            let encoding = *self.encoding.read().await;
+            let mut documents = self.documents.write().await;
+            let Some(doc) = documents.get_mut(&params.text_document.uri) else {
+                return;
+            };
+            for change in params.content_changes {
+                doc.apply_change(change, encoding);
+            }
+        }
+        self.schedule_diagnostics();
+    }
+
+    async fn did_save(&self, _params: DidSaveTextDocumentParams) {
+        self.schedule_diagnostics();
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.documents
+            .write()
+            .await
+            .remove(&params.text_document.uri);
+        if let Some(diags) = self.diagnostics.write().await.remove(&params.text_document.uri) {
+            if !diags.is_empty() {
+                self.client
+                    .publish_diagnostics(params.text_document.uri, vec![], None)
+                    .await;
+            }
+        }
+    }
+
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
-        eprintln!("complete: {:?}", params);
-        Ok(None)
+        let text_document_position = &params.text_document_position;
+        let documents = self.documents.read().await;
+        let Some(doc) = documents.get(&text_document_position.text_document.uri) else {
+            return Ok(None);
+        };
+        let Some(line) = doc.line(text_document_position.position.line) else {
+            return Ok(None);
+        };
+        let encoding = *self.encoding.read().await;
+        let byte = line_byte_offset(line, text_document_position.position.character, encoding);
+        let prefix = &line[..byte];
+
+        let Some(cap) = COMPLETION_PATH_RE.captures(prefix) else {
+            return Ok(None);
+        };
+        let path = &cap[2];
+
+        let items = match &cap[1] {
+            "Values" => Self::complete_yaml_path(&*self.chart.values.read().await, path),
+            "Chart" => Self::complete_chart_path(&*self.chart.metadata.read().await, path),
+            "Release" => Self::complete_release(path),
+            _ => Vec::new(),
+        };
+
+        if items.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(CompletionResponse::Array(items)))
+        }
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let text_document_position = &params.text_document_position_params;
+        let documents = self.documents.read().await;
+        let Some(doc) = documents.get(&text_document_position.text_document.uri) else {
+            return Ok(None);
+        };
+        let Some(line) = doc.line(text_document_position.position.line) else {
+            return Ok(None);
+        };
+        let encoding = *self.encoding.read().await;
+        let Some(word) = Self::word_at(line, text_document_position.position.character, encoding)
+        else {
+            return Ok(None);
+        };
+        let Some(mat) = REFERENCE_RE.captures(word) else {
+            return Ok(None);
+        };
+        let path = &mat[2];
+
+        let markdown = match &mat[1] {
+            "Values" => {
+                let values = self.chart.values.read().await;
+                let Some(value) = Self::resolve_yaml_path(&values, path) else {
+                    return Ok(None);
+                };
+                Self::render_yaml_hover(value)
+            }
+            "Chart" => {
+                let metadata = self.chart.metadata.read().await;
+                let Some(value) = Self::resolve_chart_path(&metadata, path) else {
+                    return Ok(None);
+                };
+                Self::render_yaml_hover(value)
+            }
+            "Release" => {
+                let field = path.trim_start_matches('.');
+                let Some((_, desc)) = RELEASE_FIELDS.iter().find(|(name, _)| *name == field)
+                else {
+                    return Ok(None);
+                };
+                desc.to_string()
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: markdown,
+            }),
+            range: None,
+        }))
     }
 
     async fn goto_definition(
         &self,
         params: GotoDefinitionParams,
     ) -> Result<Option<GotoDefinitionResponse>> {
-        let path = params
-            .text_document_position_params
-            .text_document
-            .uri
-            .path();
-        let mut lines = BufReader::new(fs::File::open(path).await.unwrap()).lines();
-
-        let mut lineno = 0;
+        let uri = &params.text_document_position_params.text_document.uri;
+        let encoding = *self.encoding.read().await;
+        let documents = self.documents.read().await;
+        let Some(doc) = documents.get(uri) else {
+            return Ok(None);
+        };
 
         let mut ctx = Context::new(Scope::new(Statement::Global));
 
         let position = &params.text_document_position_params.position;
-        while let Some(line) = lines.next_line().await.unwrap() {
+        for (lineno, line) in doc.lines().enumerate() {
+            let lineno = lineno as u32;
+            let line = line.to_string();
             if lineno == position.line {
+                if let Some(name) = Self::helper_name_at(&line, position.character, encoding) {
+                    let helpers = self.helpers.read().await;
+                    return Ok(helpers
+                        .get(&name)
+                        .cloned()
+                        .map(GotoDefinitionResponse::Scalar));
+                }
+
                 eprintln!("goto_definition: {:?}", position);
-                let (start, _) = line
-                    .chars()
-                    .enumerate()
-                    .take(position.character as usize)
-                    .filter(|(_idx, c)| *c == ' ')
-                    .last()
-                    .unwrap();
-                let (end, _) = line
-                    .chars()
-                    .enumerate()
-                    .skip(position.character as usize)
-                    .filter(|(_idx, c)| *c == ' ')
-                    .next()
-                    .unwrap();
-                let key = &line[start + 1..end];
+                let Some(key) = Self::word_at(&line, position.character, encoding) else {
+                    return Ok(None);
+                };
                 eprintln!("ctx: {}, {:?}", key, ctx);
                 if let Some(var) = ctx.get_var(&key.into()) {
                     eprintln!("definition: {:?}", var);
@@ -362,8 +1116,67 @@ impl LanguageServer for Backend {
                     _ => {}
                 }
             }
+        }
+        Ok(None)
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<lsp_types::Location>>> {
+        let text_document_position = &params.text_document_position;
+        let encoding = *self.encoding.read().await;
+        let name = {
+            let documents = self.documents.read().await;
+            let Some(doc) = documents.get(&text_document_position.text_document.uri) else {
+                return Ok(None);
+            };
+            let Some(line) = doc.line(text_document_position.position.line) else {
+                return Ok(None);
+            };
+            let Some(name) =
+                Self::helper_name_at(line, text_document_position.position.character, encoding)
+            else {
+                return Ok(None);
+            };
+            name
+        };
+
+        let mut locations = self
+            .helper_refs
+            .read()
+            .await
+            .get(&name)
+            .cloned()
+            .unwrap_or_default();
+        if params.context.include_declaration {
+            if let Some(def) = self.helpers.read().await.get(&name) {
+                locations.push(def.clone());
+            }
+        }
 
-            lineno += 1;
+        if locations.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(locations))
+        }
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let relevant = params.changes.iter().any(|change| {
+            let path = change.uri.path();
+            path.ends_with("values.yaml")
+                || path.ends_with("Chart.yaml")
+                || path.ends_with(".tpl")
+        });
+        if relevant {
+            self.spawn_reload_chart();
+        }
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        if params.command == "helmls/reload" {
+            self.spawn_reload_chart();
         }
         Ok(None)
     }
@@ -378,7 +1191,7 @@ async fn main() {
 
     let (service, socket) = LspService::new(|client| Backend {
         client,
-        chart: Default::default(),
+        shared: Arc::new(Shared::default()),
     });
     Server::new(stdin, stdout, socket).serve(service).await;
 }